@@ -0,0 +1,150 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// A parsed `Cache-Control` header, modeled as a set of directives rather
+// than the raw header text, following the approach of the `headers` crate.
+// See https://tools.ietf.org/html/rfc7234#section-5.2 for the grammar.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub private: bool,
+    pub public: bool,
+    pub max_age: Option<u64>,
+    pub s_maxage: Option<u64>,
+}
+
+// Splits a Cache-Control header value on top-level commas, the way RFC 7234
+// §1.2.2's quoted-string grammar requires: a comma inside a double-quoted
+// argument (e.g. `no-cache="X-Foo, X-Bar"`) does not end the directive.
+fn split_directives(value: &str) -> Vec<&str> {
+    let mut directives = vec![];
+    let mut start = 0;
+    let mut in_quotes = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                directives.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    directives.push(&value[start..]);
+    directives
+}
+
+impl CacheControl {
+    // Parses a `Cache-Control` header value into its directives. Unknown
+    // directives are ignored, and a later occurrence of a directive
+    // overrides an earlier one.
+    pub fn parse(value: &str) -> Self {
+        let mut cache_control = CacheControl::default();
+        for directive in split_directives(value) {
+            let directive = directive.trim();
+            if directive.is_empty() {
+                continue;
+            }
+            let (name, arg) = match directive.find('=') {
+                Some(i) => (&directive[..i], Some(directive[i + 1..].trim())),
+                None => (directive, None),
+            };
+            let name = name.trim().to_ascii_lowercase();
+            // Arguments may be wrapped in double quotes, e.g. `no-cache="field"`.
+            let arg = arg.map(|arg| arg.trim_matches('"'));
+            match name.as_str() {
+                "no-cache" => cache_control.no_cache = true,
+                "no-store" => cache_control.no_store = true,
+                "private" => cache_control.private = true,
+                "public" => cache_control.public = true,
+                "max-age" => cache_control.max_age = arg.and_then(|arg| arg.parse().ok()),
+                "s-maxage" => cache_control.s_maxage = arg.and_then(|arg| arg.parse().ok()),
+                _ => {}
+            }
+        }
+        cache_control
+    }
+    // The freshness lifetime the Google SXG cache would use, preferring
+    // `s-maxage` over `max-age` per
+    // https://tools.ietf.org/html/rfc7234#section-5.2.2.9.
+    pub fn freshness_lifetime(&self) -> Option<u64> {
+        self.s_maxage.or(self.max_age)
+    }
+    // Returns an error describing the first directive that makes the
+    // response unfit for the Google SXG cache, per
+    // https://github.com/google/webpackager/blob/master/docs/cache_requirements.md#user-content-google-sxg-cache
+    pub fn validate_for_sxg_cache(&self) -> Result<(), &'static str> {
+        if self.no_store {
+            return Err("no-store");
+        }
+        if self.no_cache {
+            return Err("no-cache");
+        }
+        if self.private {
+            return Err("private");
+        }
+        match self.freshness_lifetime() {
+            Some(lifetime) if lifetime > 0 => Ok(()),
+            _ => Err("missing a positive max-age or s-maxage"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn whitespace_around_commas_is_ignored() {
+        let cache_control = CacheControl::parse(" max-age=600 , public ");
+        assert_eq!(cache_control.max_age, Some(600));
+        assert!(cache_control.public);
+    }
+    #[test]
+    fn duplicate_directives_last_wins() {
+        let cache_control = CacheControl::parse("max-age=100, max-age=200");
+        assert_eq!(cache_control.max_age, Some(200));
+    }
+    #[test]
+    fn quoted_argument_with_comma_is_not_split() {
+        let cache_control = CacheControl::parse(r#"no-cache="X-Foo, X-Bar", max-age=600"#);
+        assert!(cache_control.no_cache);
+        assert_eq!(cache_control.max_age, Some(600));
+    }
+    #[test]
+    fn s_maxage_takes_priority_over_max_age() {
+        let cache_control = CacheControl::parse("max-age=100, s-maxage=200");
+        assert_eq!(cache_control.freshness_lifetime(), Some(200));
+    }
+    #[test]
+    fn fresh_public_response_is_valid_for_sxg_cache() {
+        assert_eq!(CacheControl::parse("public, max-age=600").validate_for_sxg_cache(), Ok(()));
+    }
+    #[test]
+    fn no_store_is_rejected() {
+        assert_eq!(CacheControl::parse("no-store, max-age=600").validate_for_sxg_cache(), Err("no-store"));
+    }
+    #[test]
+    fn private_is_rejected() {
+        assert_eq!(CacheControl::parse("private, max-age=600").validate_for_sxg_cache(), Err("private"));
+    }
+    #[test]
+    fn missing_freshness_lifetime_is_rejected() {
+        assert_eq!(CacheControl::parse("public").validate_for_sxg_cache(), Err("missing a positive max-age or s-maxage"));
+    }
+    #[test]
+    fn zero_max_age_is_rejected() {
+        assert_eq!(CacheControl::parse("max-age=0").validate_for_sxg_cache(), Err("missing a positive max-age or s-maxage"));
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Read;
+
+// Decodes `body` according to the origin's `Content-Encoding`, mirroring
+// the gzip/brotli support reqwest exposes on its client. The MICE digest
+// in a signed exchange is computed over the identity representation of
+// the payload, so any origin compression must be undone before MICE
+// digesting, or the exchange's `mi-sha256-03` claim would not match what
+// it actually serves.
+//
+// Chained codings (e.g. `gzip, gzip`) are listed in the order they were
+// applied, so they are decoded right-to-left, i.e. in reverse of that order.
+pub fn decode_body(content_encoding: &str, body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = body.to_vec();
+    for coding in content_encoding.split(',').rev() {
+        let coding = coding.trim().to_ascii_lowercase();
+        body = match coding.as_str() {
+            "identity" | "" => body,
+            "gzip" | "x-gzip" => {
+                let mut decoder = flate2::read::GzDecoder::new(&body[..]);
+                let mut decoded = vec![];
+                decoder.read_to_end(&mut decoded).map_err(|e| format!("Failed to gunzip body: {}", e))?;
+                decoded
+            }
+            "deflate" => {
+                // HTTP's `Content-Encoding: deflate` is zlib-wrapped (RFC 1950),
+                // not raw DEFLATE (RFC 1951), matching reqwest's own deflate
+                // support. A few servers mistakenly send raw DEFLATE anyway,
+                // so fall back to that if the zlib framing doesn't parse.
+                let mut decoder = flate2::read::ZlibDecoder::new(&body[..]);
+                let mut decoded = vec![];
+                if decoder.read_to_end(&mut decoded).is_err() {
+                    decoded.clear();
+                    let mut decoder = flate2::read::DeflateDecoder::new(&body[..]);
+                    decoder.read_to_end(&mut decoded).map_err(|e| format!("Failed to inflate body: {}", e))?;
+                }
+                decoded
+            }
+            "br" => {
+                let mut decoded = vec![];
+                brotli::Decompressor::new(&body[..], 4096).read_to_end(&mut decoded)
+                    .map_err(|e| format!("Failed to un-brotli body: {}", e))?;
+                decoded
+            }
+            _ => return Err(format!(r#"Unsupported content-encoding "{}"."#, coding)),
+        };
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    #[test]
+    fn identity_is_passthrough() {
+        assert_eq!(decode_body("identity", b"hello").unwrap(), b"hello");
+    }
+    #[test]
+    fn unknown_coding_is_rejected() {
+        assert!(decode_body("compress", b"hello").is_err());
+    }
+    #[test]
+    fn gzip_round_trips() {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(b"hello sxg").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decode_body("gzip", &compressed).unwrap(), b"hello sxg");
+    }
+    #[test]
+    fn zlib_wrapped_deflate_round_trips() {
+        // Per RFC 7230/2616, `Content-Encoding: deflate` is zlib-wrapped.
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(b"hello sxg").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decode_body("deflate", &compressed).unwrap(), b"hello sxg");
+    }
+    #[test]
+    fn raw_deflate_also_round_trips_via_fallback() {
+        let mut encoder = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(b"hello sxg").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decode_body("deflate", &compressed).unwrap(), b"hello sxg");
+    }
+    #[test]
+    fn chained_gzip_is_decoded_in_reverse_application_order() {
+        let mut inner = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        inner.write_all(b"hello sxg").unwrap();
+        let once = inner.finish().unwrap();
+        let mut outer = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        outer.write_all(&once).unwrap();
+        let twice = outer.finish().unwrap();
+        assert_eq!(decode_body("gzip, gzip", &twice).unwrap(), b"hello sxg");
+    }
+}
@@ -12,35 +12,85 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use once_cell::sync::Lazy;
 
-pub struct Headers(HashMap<String, String>);
+// An insertion-ordered, multi-valued header collection, modeled after
+// hyper's `HeaderMap`. A plain `HashMap<String, String>` cannot represent
+// repeated fields (e.g. multiple `Set-Cookie` headers) and does not
+// preserve the order in which the origin sent them, both of which matter
+// for faithfully signing an exchange.
+pub struct Headers(Vec<(String, Vec<String>)>);
 
 type Entries = Vec<(String, String)>;
 
 impl Headers {
     pub fn new(data: Entries) -> Self {
-        let mut headers = Headers(HashMap::new());
+        let mut headers = Headers(vec![]);
         for (mut k, v) in data.into_iter() {
             k.make_ascii_lowercase();
-            headers.0.insert(k, v);
+            headers.insert(k, v);
         }
         headers
     }
-    pub fn forward_to_origin_server(self, forwarded_header_names: &HashSet<String>) -> Result<Entries, String> {
-        let accept = self.0.get("accept").ok_or("The request does not have accept header")?;
-        crate::media_type::validate_sxg_request_header(accept)?;
+    // Appends a value to the name's existing entry, preserving insertion
+    // order, or creates a new entry if the name hasn't been seen yet.
+    fn insert(&mut self, name: String, value: String) {
+        if let Some((_, values)) = self.0.iter_mut().find(|(k, _)| *k == name) {
+            values.push(value);
+        } else {
+            self.0.push((name, vec![value]));
+        }
+    }
+    // Returns the comma-joined value of all occurrences of `name`, which is
+    // the standard way to combine repeated header fields per
+    // https://tools.ietf.org/html/rfc7230#section-3.2.2.
+    fn get_joined(&self, name: &str) -> Option<String> {
+        self.0.iter().find(|(k, _)| k == name).map(|(_, v)| v.join(", "))
+    }
+    fn contains_key(&self, name: &str) -> bool {
+        self.0.iter().any(|(k, _)| k == name)
+    }
+    fn remove(&mut self, name: &str) {
+        self.0.retain(|(k, _)| k != name);
+    }
+    fn set(&mut self, name: &str, value: String) {
+        self.remove(name);
+        self.0.push((name.to_string(), vec![value]));
+    }
+    // Decodes `body` to its identity representation according to this
+    // response's `content-encoding`, and returns the decoded body together
+    // with a copy of `self` whose `content-encoding` and `content-length`
+    // reflect that decoding. `get_signed_headers_bytes` always advertises
+    // `mi-sha256-03` on top of this, so the origin's own coding must not
+    // leak into the signed exchange.
+    pub fn decode_body(&self, body: &[u8]) -> Result<(Vec<u8>, Self), String> {
+        let mut headers = Headers(self.0.clone());
+        let body = match headers.get_joined("content-encoding") {
+            Some(content_encoding) => {
+                let body = crate::content_encoding::decode_body(&content_encoding, body)?;
+                headers.remove("content-encoding");
+                headers.set("content-length", body.len().to_string());
+                body
+            }
+            None => body.to_vec(),
+        };
+        Ok((body, headers))
+    }
+    pub fn forward_to_origin_server(self, forwarded_header_names: &HashSet<String>) -> Result<(Entries, crate::media_type::Negotiation), String> {
+        let accept = self.get_joined("accept").ok_or("The request does not have accept header")?;
+        let negotiation = crate::media_type::negotiate(&accept)
+            .ok_or_else(|| format!(r#"The accept header "{}" does not accept signed-exchange or html."#, accept))?;
         // Set Via per https://tools.ietf.org/html/rfc7230#section-5.7.1
         let mut via = format!("sxgrs");
-        if let Some(upstream_via) = self.0.get("via") {
+        if let Some(upstream_via) = self.get_joined("via") {
             via = format!("{}, {}", upstream_via, via);
         }
-        let mut new_headers: HashMap<String, String> = self.0.into_iter().filter_map(|(k, v)| {
+        let mut new_headers: Entries = self.0.into_iter().filter_map(|(k, values)| {
             let v = if forwarded_header_names.contains(&k) {
-                v
+                values.join(", ")
             } else if k == "via" {
-                format!("{}, {}", v, via)
+                via.clone()
             } else {
                 return None;
             };
@@ -53,26 +103,27 @@ impl Headers {
             ("via", &via),
         ];
         for (k, v) in default_values {
-            if new_headers.contains_key(k) == false {
-                new_headers.insert(k.to_string(), v.to_string());
+            if new_headers.iter().any(|(name, _)| name == k) == false {
+                new_headers.push((k.to_string(), v.to_string()));
             }
         }
-        Ok(new_headers.into_iter().collect())
+        Ok((new_headers, negotiation))
     }
     pub fn validate_as_sxg_payload(&self, reject_stateful_headers: bool) -> Result<(), String> {
-        for (k, v) in self.0.iter() {
+        for (k, values) in self.0.iter() {
             if reject_stateful_headers && STATEFUL_HEADERS.contains(k.as_str()) {
                 return Err(format!(r#"A stateful header "{}" is found."#, k));
             }
             if k == "cache-control" {
                 // https://github.com/google/webpackager/blob/master/docs/cache_requirements.md#user-content-google-sxg-cache
-                if v.contains("no-cache") || v.contains("private") {
-                    return Err(format!(r#"The cache-control header is "{}"."#, v));
+                let v = values.join(", ");
+                if let Err(reason) = crate::cache_control::CacheControl::parse(&v).validate_for_sxg_cache() {
+                    return Err(format!(r#"The cache-control header "{}" is not cacheable: {}."#, v, reason));
                 }
             }
         }
         // Google SXG cache sets the maximum of SXG to be 8 megabytes.
-        if let Some(size) = self.0.get("content-length") {
+        if let Some(size) = self.get_joined("content-length") {
             if let Ok(size) = size.parse::<u64>() {
                 const MAX_SIZE: u64 = 8_000_000;
                 if size > MAX_SIZE {
@@ -84,22 +135,28 @@ impl Headers {
         }
         // The payload of SXG must have a content-type. See step 8 of
         // https://wicg.github.io/webpackage/draft-yasskin-httpbis-origin-signed-exchanges-impl.html#name-signature-validity
-        if self.0.contains_key("content-type") == false {
+        if self.contains_key("content-type") == false {
             return Err(format!("The content-type header is missing."));
         }
         Ok(())
     }
     pub fn get_signed_headers_bytes(&self, status_code: u16, mice_digest: &[u8]) -> Vec<u8> {
         use crate::cbor::DataItem;
-        let mut entries: Vec<(&str, &str)> = vec![];
-        for (k, v) in self.0.iter() {
+        // Repeated header fields are joined with a comma per
+        // https://wicg.github.io/webpackage/draft-yasskin-http-origin-signed-responses.html#rfc.section.3.2,
+        // so the signed CBOR map keeps exactly one entry per header name and
+        // the signature stays deterministic regardless of how the origin
+        // split its values across wire lines.
+        let joined: Vec<(&str, String)> = self.0.iter().filter_map(|(k, values)| {
             if UNCACHED_HEADERS.contains(k.as_str()) || STATEFUL_HEADERS.contains(k.as_str()) {
-                continue;
+                None
+            } else {
+                Some((k.as_str(), values.join(", ")))
             }
-            entries.push((k, v));
-        }
+        }).collect();
         let status_code = status_code.to_string();
         let digest = format!("mi-sha256-03={}", ::base64::encode(&mice_digest));
+        let mut entries: Vec<(&str, &str)> = joined.iter().map(|(k, v)| (*k, v.as_str())).collect();
         entries.push((":status", &status_code));
         entries.push(("content-encoding", "mi-sha256-03"));
         entries.push(("digest", &digest));
@@ -153,3 +210,52 @@ static STATEFUL_HEADERS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
     ].into_iter().collect()
 });
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn headers_from(pairs: &[(&str, &str)]) -> Headers {
+        Headers::new(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+    #[test]
+    fn repeated_header_is_preserved_in_insertion_order() {
+        let headers = headers_from(&[("set-cookie", "a=1"), ("set-cookie", "b=2")]);
+        assert_eq!(headers.get_joined("set-cookie"), Some("a=1, b=2".to_string()));
+    }
+    #[test]
+    fn forward_to_origin_server_joins_repeated_forwarded_headers() {
+        let headers = headers_from(&[
+            ("accept", "text/html"),
+            ("link", "<a>; rel=preload"),
+            ("link", "<b>; rel=preload"),
+        ]);
+        let mut forwarded = HashSet::new();
+        forwarded.insert("link".to_string());
+        let (entries, _) = headers.forward_to_origin_server(&forwarded).unwrap();
+        let link = entries.iter().find(|(k, _)| k == "link").unwrap();
+        assert_eq!(link.1, "<a>; rel=preload, <b>; rel=preload");
+    }
+    #[test]
+    fn get_signed_headers_bytes_is_deterministic() {
+        let headers = headers_from(&[("content-type", "text/html"), ("link", "<a>"), ("link", "<b>")]);
+        let first = headers.get_signed_headers_bytes(200, b"digest");
+        let second = headers.get_signed_headers_bytes(200, b"digest");
+        assert_eq!(first, second);
+    }
+    #[test]
+    fn get_signed_headers_bytes_excludes_uncached_and_stateful_headers() {
+        let with_cookie = headers_from(&[("content-type", "text/html"), ("connection", "close"), ("set-cookie", "a=1")]);
+        let without_cookie = headers_from(&[("content-type", "text/html")]);
+        assert_eq!(
+            with_cookie.get_signed_headers_bytes(200, b"digest"),
+            without_cookie.get_signed_headers_bytes(200, b"digest"),
+        );
+    }
+    #[test]
+    fn decode_body_strips_content_encoding_and_fixes_length() {
+        let headers = headers_from(&[("content-encoding", "identity"), ("content-length", "0")]);
+        let (body, decoded_headers) = headers.decode_body(b"hello").unwrap();
+        assert_eq!(body, b"hello");
+        assert_eq!(decoded_headers.get_joined("content-encoding"), None);
+        assert_eq!(decoded_headers.get_joined("content-length"), Some("5".to_string()));
+    }
+}
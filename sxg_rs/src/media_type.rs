@@ -0,0 +1,135 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+const SXG_MEDIA_TYPE: &str = "application/signed-exchange";
+
+// One weighted entry of an `Accept` header, e.g.
+// `application/signed-exchange;v=b3;q=0.9`. Modeled after how typed header
+// libraries parse quality items (`qitem`), splitting the media range from
+// its `;`-separated parameters and reading `q` as the preference weight.
+#[derive(Debug, PartialEq)]
+struct AcceptItem {
+    media_type: String,
+    sxg_version: Option<String>,
+    q: f32,
+}
+
+impl AcceptItem {
+    fn parse(item: &str) -> Option<Self> {
+        let mut parts = item.split(';').map(str::trim);
+        let media_type = parts.next()?.to_ascii_lowercase();
+        if media_type.is_empty() {
+            return None;
+        }
+        let mut sxg_version = None;
+        let mut q = 1.0;
+        for param in parts {
+            let (name, value) = param.split_once('=')?;
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim().trim_matches('"');
+            match name.as_str() {
+                "v" => sxg_version = Some(value.to_string()),
+                "q" => q = value.parse::<f32>().unwrap_or(1.0).clamp(0.0, 1.0),
+                _ => {}
+            }
+        }
+        Some(AcceptItem { media_type, sxg_version, q })
+    }
+    // Note that `*/*` is a catch-all, not an opt-in: plenty of non-SXG
+    // clients (curl, crawlers) send it, so it must not be treated as SXG
+    // acceptance.
+    fn is_sxg(&self) -> bool {
+        self.media_type == SXG_MEDIA_TYPE
+    }
+}
+
+// The outcome of negotiating an `Accept` header against SXG and HTML,
+// giving callers (e.g. a proxy in front of the origin) enough information
+// to decide whether to serve a signed exchange or fall back to the
+// unsigned resource.
+#[derive(Debug, PartialEq)]
+pub enum Negotiation {
+    Sxg { version: String },
+    Html,
+}
+
+// Parses `accept` and decides between SXG and HTML. Real SXG-capable
+// clients (e.g. Chrome) send `text/html` as their top-ranked, implicit-q=1
+// range and `application/signed-exchange;v=b3` at a lower explicit q, e.g.
+// `text/html,...,application/signed-exchange;v=b3;q=0.9` — so "highest q
+// wins" would pick `text/html` and never serve SXG. Instead, SXG is
+// signaled by presence: whenever an acceptable `application/signed-exchange`
+// range appears anywhere in the header, it wins regardless of its rank
+// against `text/html`.
+pub fn negotiate(accept: &str) -> Option<Negotiation> {
+    let items: Vec<AcceptItem> = accept.split(',').filter_map(AcceptItem::parse).collect();
+    if let Some(item) = items.iter().find(|item| item.is_sxg() && item.q > 0.0) {
+        return Some(Negotiation::Sxg {
+            version: item.sxg_version.clone().unwrap_or_else(|| "b3".to_string()),
+        });
+    }
+    if items.iter().any(|item| item.q > 0.0 && (item.media_type == "text/html" || item.media_type == "text/*" || item.media_type == "*/*")) {
+        return Some(Negotiation::Html);
+    }
+    None
+}
+
+// Validates that `accept` allows for an `application/signed-exchange`
+// response at all, used to decide whether it is worth generating one.
+pub fn validate_sxg_request_header(accept: &str) -> Result<(), String> {
+    let accepts_sxg = accept.split(',').filter_map(AcceptItem::parse).any(|item| item.is_sxg() && item.q > 0.0);
+    if accepts_sxg {
+        Ok(())
+    } else {
+        Err(format!(r#"The accept header "{}" does not accept signed-exchange."#, accept))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn chrome_accept_header_serves_sxg_despite_lower_q() {
+        // Chrome's real Accept header: text/html is implicit q=1, SXG is an
+        // explicit but lower q=0.9. Presence, not rank, should decide.
+        let accept = "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8,application/signed-exchange;v=b3;q=0.9";
+        assert_eq!(negotiate(accept), Some(Negotiation::Sxg { version: "b3".to_string() }));
+    }
+    #[test]
+    fn html_only_accept_header_serves_html() {
+        assert_eq!(negotiate("text/html"), Some(Negotiation::Html));
+    }
+    #[test]
+    fn wildcard_only_accept_header_does_not_serve_sxg() {
+        assert_eq!(negotiate("*/*"), Some(Negotiation::Html));
+        assert!(validate_sxg_request_header("*/*").is_err());
+    }
+    #[test]
+    fn zero_q_sxg_is_not_acceptable() {
+        assert_eq!(negotiate("text/html,application/signed-exchange;v=b3;q=0"), Some(Negotiation::Html));
+    }
+    #[test]
+    fn malformed_q_value_defaults_to_acceptable() {
+        let item = AcceptItem::parse("application/signed-exchange;v=b3;q=not-a-number").unwrap();
+        assert_eq!(item.q, 1.0);
+    }
+    #[test]
+    fn missing_v_parameter_still_negotiates_sxg_with_default_version() {
+        assert_eq!(negotiate("application/signed-exchange;q=0.9"), Some(Negotiation::Sxg { version: "b3".to_string() }));
+    }
+    #[test]
+    fn no_acceptable_representation_returns_none() {
+        assert_eq!(negotiate("application/json"), None);
+    }
+}